@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use reqwest::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+
+mod middleware;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HttpRequest {
@@ -10,6 +11,10 @@ pub struct HttpRequest {
     pub body: Option<String>,
     pub username: Option<String>,
     pub password: Option<String>,
+    /// Enables the built-in request/response logging middleware.
+    pub enable_logging: Option<bool>,
+    /// Enables the built-in retry-with-backoff middleware for 5xx/connection errors.
+    pub enable_retry: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,6 +23,25 @@ pub struct HttpResponse {
     pub headers: HashMap<String, String>,
     pub body: String,
     pub success: bool,
+    /// `None` for a plain UTF-8 text `body`, `Some("base64")` when `body`
+    /// holds base64 instead (non-text `Content-Type`), or `Some("multipart")`
+    /// when this is a `multipart/*` response and `parts` carries the payload
+    /// instead of `body`.
+    pub encoding: Option<String>,
+    /// Populated instead of `body` for `multipart/*` responses: one entry
+    /// per body part, each decoded according to its own `Content-Type`.
+    pub parts: Option<Vec<MultipartPart>>,
+}
+
+/// A single part of a `multipart/*` response, with its own headers and
+/// payload decoded (or base64-encoded, for non-text parts) independently of
+/// the rest of the response.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MultipartPart {
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    /// `"text"` or `"base64"`, mirroring [`HttpResponse::encoding`].
+    pub encoding: String,
 }
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -26,152 +50,76 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
-fn generate_digest_auth(username: &str, password: &str, method: &str, url: &str, www_auth: &str) -> Result<String, String> {
-    use std::collections::HashMap;
-    
-    // Parse the digest challenge
-    let mut challenge = HashMap::new();
-    let auth_str = www_auth.replace("Digest ", "");
-    
-    for part in auth_str.split(',') {
-        let part = part.trim();
-        if let Some((key, value)) = part.split_once('=') {
-            let key = key.trim();
-            let value = value.trim().trim_matches('"');
-            challenge.insert(key, value);
-        }
-    }
-    
-    let realm = challenge.get("realm").unwrap_or(&"");
-    let nonce = challenge.get("nonce").unwrap_or(&"");
-    let qop = challenge.get("qop").unwrap_or(&"");
-    
-    // Parse URL to get path
-    let url_obj = url::Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
-    let mut uri = url_obj.path().to_string();
-    if let Some(query) = url_obj.query() {
-        uri = format!("{}?{}", uri, query);
-    }
-    
-    // Generate cnonce and nc
-    let cnonce = format!("{:x}", rand::random::<u64>());
-    let nc = "00000001";
-    
-    // Calculate HA1
-    let ha1_input = format!("{}:{}:{}", username, realm, password);
-    let ha1 = format!("{:x}", md5::compute(ha1_input.as_bytes()));
-    
-    // Calculate HA2
-    let ha2_input = format!("{}:{}", method, uri);
-    let ha2 = format!("{:x}", md5::compute(ha2_input.as_bytes()));
-    
-    // Calculate response
-    let response = if !qop.is_empty() {
-        let response_input = format!("{}:{}:{}:{}:{}:{}", ha1, nonce, nc, cnonce, qop, ha2);
-        format!("{:x}", md5::compute(response_input.as_bytes()))
-    } else {
-        let response_input = format!("{}:{}:{}", ha1, nonce, ha2);
-        format!("{:x}", md5::compute(response_input.as_bytes()))
-    };
-    
-    // Build Authorization header
-    let mut auth_header = format!(
-        r#"Digest username="{}", realm="{}", nonce="{}", uri="{}", response="{}""#,
-        username, realm, nonce, uri, response
-    );
-    
-    if !qop.is_empty() {
-        auth_header.push_str(&format!(r#", qop={}, nc={}, cnonce="{}""#, qop, nc, cnonce));
-    }
-    
-    if let Some(opaque) = challenge.get("opaque") {
-        auth_header.push_str(&format!(r#", opaque="{}""#, opaque));
-    }
-    
-    Ok(auth_header)
-}
-
 #[tauri::command]
 async fn http_request(request: HttpRequest) -> Result<HttpResponse, String> {
     let client = reqwest::Client::new();
-    
-    let mut req_builder = match request.method.to_uppercase().as_str() {
-        "GET" => client.get(&request.url),
-        "POST" => client.post(&request.url),
-        "PUT" => client.put(&request.url),
-        "DELETE" => client.delete(&request.url),
-        _ => return Err(format!("Unsupported HTTP method: {}", request.method)),
+
+    let req_parts = middleware::RequestParts {
+        method: request.method,
+        url: request.url,
+        headers: request.headers.unwrap_or_default(),
+        body: request.body.map(|b| b.into_bytes()),
+        username: request.username,
+        password: request.password,
     };
 
-    // Add headers
-    if let Some(headers) = request.headers {
-        for (key, value) in headers {
-            req_builder = req_builder.header(&key, &value);
-        }
+    // Auth must sit outside retry: it negotiates the Authorization header
+    // (probing for a challenge, picking a fresh nonce/cnonce) once up front,
+    // then retry only re-sends the already-authenticated request below it.
+    // Putting retry outside auth would re-probe and re-negotiate on every
+    // attempt, multiplying real requests against the server.
+    let mut chain: Vec<Box<dyn middleware::Middleware>> = Vec::new();
+    if request.enable_logging.unwrap_or(false) {
+        chain.push(Box::new(middleware::LoggingMiddleware));
     }
-
-    // Add body before attempting authentication so the challenge request
-    // matches the actual request the server will receive. Some endpoints
-    // (like MarkLogic's evaler.xqy) require a POST body even for the
-    // initial 401 challenge.
-    if let Some(body) = request.body {
-        req_builder = req_builder.body(body);
+    chain.push(Box::new(middleware::AuthMiddleware));
+    if request.enable_retry.unwrap_or(false) {
+        chain.push(Box::new(middleware::RetryMiddleware {
+            config: middleware::RetryConfig::default(),
+        }));
     }
+    chain.push(Box::new(middleware::CorsMiddleware));
 
-    // Handle authentication - try digest first, fallback to basic
-    if let (Some(username), Some(password)) = (request.username, request.password) {
-        // First, make a request without auth to get the challenge
-        let challenge_response = req_builder.try_clone().unwrap().send().await;
-        
-        if let Ok(response) = challenge_response {
-            if response.status() == 401 {
-                // Check for digest challenge
-                if let Some(www_auth) = response.headers().get(WWW_AUTHENTICATE) {
-                    if let Ok(auth_str) = www_auth.to_str() {
-                        if auth_str.starts_with("Digest") {
-                            // Parse digest challenge and generate response
-                            if let Ok(digest_auth) = generate_digest_auth(&username, &password, &request.method, &request.url, auth_str) {
-                                req_builder = req_builder.header(AUTHORIZATION, digest_auth);
-                            }
-                        }
-                    }
-                }
-            }
-        } else {
-            // If challenge request fails, fallback to basic auth
-            req_builder = req_builder.basic_auth(&username, Some(&password));
-        }
-    }
+    middleware::run_chain(&client, &chain, req_parts).await
+}
 
-    match req_builder.send().await {
-        Ok(response) => {
-            let status = response.status().as_u16();
-            let headers: HashMap<String, String> = response
-                .headers()
-                .iter()
-                .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
-                .collect();
-            
-            let body = match response.text().await {
-                Ok(text) => text,
-                Err(e) => return Err(format!("Failed to read response body: {}", e)),
-            };
+/// Streaming counterpart to [`http_request`] for large MarkLogic result sets
+/// and multi-megabyte exports: pumps the response body to `channel` chunk by
+/// chunk as raw bytes instead of buffering it all into memory, then returns
+/// the final status/headers as an ordinary [`HttpResponse`] once the body is
+/// fully read.
+#[tauri::command]
+async fn http_request_stream(
+    request: HttpRequest,
+    channel: tauri::ipc::Channel<Vec<u8>>,
+) -> Result<HttpResponse, String> {
+    let client = reqwest::Client::new();
 
-            // Add CORS headers for browser compatibility
-            let mut response_headers = headers;
-            response_headers.insert("Access-Control-Allow-Origin".to_string(), "*".to_string());
-            response_headers.insert("Access-Control-Allow-Methods".to_string(), "GET, POST, OPTIONS".to_string());
-            response_headers.insert("Access-Control-Allow-Headers".to_string(), "Content-Type".to_string());
+    let req_parts = middleware::RequestParts {
+        method: request.method,
+        url: request.url,
+        headers: request.headers.unwrap_or_default(),
+        body: request.body.map(|b| b.into_bytes()),
+        username: request.username,
+        password: request.password,
+    };
 
-            Ok(HttpResponse {
-                status,
-                headers: response_headers,
-                body,
-                success: status >= 200 && status < 300,
-            })
-        }
-        Err(e) => Err(format!("HTTP request failed: {}", e)),
+    // Same ordering as `http_request`, except retry is deliberately left out:
+    // RetryMiddleware would replay the whole send and re-pump chunks into the
+    // same channel with no boundary marker, so a 5xx or mid-stream transport
+    // error would push a failed attempt's partial bytes followed by an
+    // unrelated second attempt's bytes, silently corrupting the reconstructed
+    // body on the frontend. `enable_retry` is accepted on `HttpRequest` but
+    // has no effect here until the channel protocol can signal a restart.
+    let mut chain: Vec<Box<dyn middleware::Middleware>> = Vec::new();
+    if request.enable_logging.unwrap_or(false) {
+        chain.push(Box::new(middleware::LoggingMiddleware));
     }
+    chain.push(Box::new(middleware::AuthMiddleware));
+    chain.push(Box::new(middleware::CorsMiddleware));
+    chain.push(Box::new(middleware::StreamMiddleware { channel }));
+
+    middleware::run_chain(&client, &chain, req_parts).await
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -179,7 +127,7 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
 
-        .invoke_handler(tauri::generate_handler![greet, http_request])
+        .invoke_handler(tauri::generate_handler![greet, http_request, http_request_stream])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }